@@ -10,6 +10,9 @@ use std::io::prelude::*;
 use std::mem::size_of;
 use std::path::Path;
 
+use flate2::read::DeflateDecoder;
+use flate2::{Compress, Compression, FlushCompress, Status};
+
 #[derive(Debug, Clone)]
 pub struct Error {
   kind: ErrorKind,
@@ -18,7 +21,10 @@ pub struct Error {
 #[derive(Debug, Clone)]
 pub enum ErrorKind {
   IO(std::io::ErrorKind),
-  FromUtf8Error,
+  // When reading, the entry uses a known compression method whose decoder
+  // feature isn't enabled in this build; when writing, the method is one
+  // `ZipWriter` can't compress (anything but stored or deflate).
+  UnsupportedCompression(u16),
   Other,
 }
 
@@ -27,6 +33,8 @@ pub enum ErrorKind {
 pub enum CompressionMethod {
   Uncompressed = 0,
   Deflate = 8,
+  Bzip2 = 12,
+  Zstd = 93,
   Unsupported = u16::MAX,
 }
 
@@ -40,9 +48,15 @@ pub struct ZipArchive<R: Read + io::Seek> {
   names: HashMap<String, usize>,
 }
 
-#[derive(Debug)]
-pub struct ZipFile {
+pub struct ZipFile<'a> {
   header: ZipLocalFileHeader,
+  reader: Crc32Reader<Box<dyn Read + 'a>>,
+}
+
+impl<'a> Read for ZipFile<'a> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.reader.read(buf)
+  }
 }
 
 #[repr(C, packed)]
@@ -67,6 +81,11 @@ pub struct ZipCentralDirectoryFile {
   filename: String,
   extra: Vec<u8>,
   comment: String,
+  // widened ZIP64 values; equal to the header's fields unless the ZIP64
+  // extended-information extra field (0x0001) overrides them.
+  uncompressed_len: u64,
+  compressed_len: u64,
+  relative_offset_of_local_header: u64,
 }
 
 #[repr(C, packed)]
@@ -91,10 +110,40 @@ struct ZipCentralDirectoryFileHeader {
   relative_offset_of_local_header: u32,
 }
 
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct Zip64EndOfCentralDirectoryLocatorHeader {
+  signature: u32,
+  disk_with_zip64_eocd: u32,
+  zip64_eocd_offset: u64,
+  num_disks: u32,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+struct Zip64EndOfCentralDirectoryHeader {
+  signature: u32,
+  size: u64,
+  made_by_ver: u16,
+  min_extract_ver: u16,
+  disk_number: u32,
+  start_disk: u32,
+  num_disk_entries: u64,
+  num_entries: u64,
+  central_dir_len: u64,
+  cendral_dir_offset: u64,
+}
+
 #[derive(Debug)]
 struct ZipEndOfCentralDirectory {
   header: ZipEndOfCentralDirectoryHeader,
   comment: String,
+  // widened ZIP64 values; equal to the header's fields unless a ZIP64 EOCD
+  // record was found, in which case these hold the real (possibly > 4 GiB
+  // or > 65535 entry) values.
+  num_entries: u64,
+  central_dir_len: u64,
+  central_dir_offset: u64,
 }
 
 #[repr(C, packed)]
@@ -124,21 +173,16 @@ impl From<std::io::Error> for Error {
   }
 }
 
-impl From<std::string::FromUtf8Error> for Error {
-  fn from(error: std::string::FromUtf8Error) -> Self {
-    Error {
-      kind: ErrorKind::FromUtf8Error,
-    }
-  }
-}
-
 impl<R: Read + io::Seek> ZipArchive<R> {
   pub fn new(mut reader: R) -> Result<ZipArchive<R>> {
     let eocd = ZipEndOfCentralDirectory::find(&mut reader)?;
-    reader.seek(io::SeekFrom::Start(eocd.header.cendral_dir_offset as u64))?;
-    let mut files = Vec::with_capacity(eocd.header.num_entries as usize);
-    let mut names = HashMap::with_capacity(eocd.header.num_entries as usize);
-    for _ in 0..eocd.header.num_entries {
+    reader.seek(io::SeekFrom::Start(eocd.central_dir_offset))?;
+    // `num_entries` is attacker-controlled (it can come straight from a
+    // ZIP64 EOCD record), so it must not be trusted as an allocation size;
+    // let the Vec/HashMap grow as entries are actually read instead.
+    let mut files = Vec::new();
+    let mut names = HashMap::new();
+    for _ in 0..eocd.num_entries {
       let cdf = ZipCentralDirectoryFile::find(&mut reader)?;
       names.insert(cdf.filename.clone(), files.len());
       files.push(cdf);
@@ -154,6 +198,57 @@ impl<R: Read + io::Seek> ZipArchive<R> {
   pub fn files(&self) -> &Vec<ZipCentralDirectoryFile> {
     &self.files
   }
+
+  pub fn by_index(&mut self, i: usize) -> Result<ZipFile<'_>> {
+    let cdf = self.files.get(i).ok_or(Error {
+      kind: ErrorKind::Other,
+    })?;
+    let crc32 = cdf.header.crc32;
+    let compressed_len = cdf.compressed_len;
+    let compression_method = cdf.header.compression_method;
+    let offset = cdf.relative_offset_of_local_header;
+
+    self.reader.seek(io::SeekFrom::Start(offset))?;
+    let header = ZipLocalFileHeader::find(&mut self.reader)?;
+    let bounded: io::Take<&mut R> = (&mut self.reader).take(compressed_len);
+    let decompressed: Box<dyn Read + '_> = match compression_method {
+      CompressionMethod::Uncompressed => Box::new(bounded),
+      CompressionMethod::Deflate => Box::new(DeflateDecoder::new(bounded)),
+      #[cfg(feature = "bzip2")]
+      CompressionMethod::Bzip2 => Box::new(bzip2::read::BzDecoder::new(bounded)),
+      #[cfg(not(feature = "bzip2"))]
+      CompressionMethod::Bzip2 => {
+        return Err(Error {
+          kind: ErrorKind::UnsupportedCompression(compression_method as u16),
+        })
+      }
+      #[cfg(feature = "zstd")]
+      CompressionMethod::Zstd => Box::new(zstd::stream::read::Decoder::new(bounded)?),
+      #[cfg(not(feature = "zstd"))]
+      CompressionMethod::Zstd => {
+        return Err(Error {
+          kind: ErrorKind::UnsupportedCompression(compression_method as u16),
+        })
+      }
+      CompressionMethod::Unsupported => {
+        return Err(Error {
+          kind: ErrorKind::Other,
+        })
+      }
+    };
+
+    Ok(ZipFile {
+      header: header,
+      reader: Crc32Reader::new(decompressed, crc32),
+    })
+  }
+
+  pub fn by_name(&mut self, name: &str) -> Result<ZipFile<'_>> {
+    let i = *self.names.get(name).ok_or(Error {
+      kind: ErrorKind::Other,
+    })?;
+    self.by_index(i)
+  }
 }
 
 impl ZipArchive<File> {
@@ -171,6 +266,24 @@ const BUF_SIZE: u64 = 65536;
 const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
 const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
 const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_EXTRA_FIELD_HEADER_ID: u16 = 0x0001;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+// Bit 3: sizes/crc32 are unknown at local-header time and follow the entry's
+// data in a trailing data descriptor instead.
+const DATA_DESCRIPTOR_GENERAL_PURPOSE_FLAG: u16 = 0x0008;
+// Entry names passed to `ZipWriter` are Rust `&str`, so they're always valid
+// UTF-8; set bit 11 (see `decode_zip_text`) so readers don't mistake them
+// for CP437.
+const WRITER_GENERAL_PURPOSE_FLAG: u16 = DATA_DESCRIPTOR_GENERAL_PURPOSE_FLAG | UTF8_FLAG;
+
+// `made_by_ver`'s high byte when the creating host system is Unix.
+const HOST_SYSTEM_UNIX: u8 = 3;
+// Unix `st_mode` file-type bits, as stored in `external_attrib`'s high 16 bits.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
 
 impl ZipEndOfCentralDirectory {
   fn find<R: Read + io::Seek>(reader: &mut R) -> Result<ZipEndOfCentralDirectory> {
@@ -211,8 +324,34 @@ impl ZipEndOfCentralDirectory {
     let central_dir_len = read_le_u32(&mut bytes);
     let cendral_dir_offset = read_le_u32(&mut bytes);
     let comment_len = read_le_u16(&mut bytes);
+    let eocd_abs_pos = fsize - nbytes + start as u64;
     let start = start + size_of::<ZipEndOfCentralDirectoryHeader>();
-    let comment = String::from_utf8(buf[start..start + comment_len as usize].to_vec())?;
+    let comment = decode_best_effort(&buf[start..start + comment_len as usize]);
+
+    // ZIP64 overflow markers: counts saturate at 0xFFFF, sizes/offset at
+    // 0xFFFFFFFF when the real values don't fit the classic EOCD fields.
+    let is_zip64 = disk_number == 0xFFFF
+      || start_disk == 0xFFFF
+      || num_disk_entries == 0xFFFF
+      || num_entries == 0xFFFF
+      || central_dir_len == 0xFFFFFFFF
+      || cendral_dir_offset == 0xFFFFFFFF;
+
+    let (num_entries64, central_dir_len64, central_dir_offset64) = if is_zip64 {
+      let locator = Zip64EndOfCentralDirectoryLocatorHeader::find(reader, eocd_abs_pos)?;
+      let zip64_eocd = Zip64EndOfCentralDirectoryHeader::find(reader, locator.zip64_eocd_offset)?;
+      (
+        zip64_eocd.num_entries,
+        zip64_eocd.central_dir_len,
+        zip64_eocd.cendral_dir_offset,
+      )
+    } else {
+      (
+        num_entries as u64,
+        central_dir_len as u64,
+        cendral_dir_offset as u64,
+      )
+    };
 
     let header = ZipEndOfCentralDirectoryHeader {
       signature: signature,
@@ -228,6 +367,85 @@ impl ZipEndOfCentralDirectory {
     Ok(ZipEndOfCentralDirectory {
       header: header,
       comment: comment,
+      num_entries: num_entries64,
+      central_dir_len: central_dir_len64,
+      central_dir_offset: central_dir_offset64,
+    })
+  }
+}
+
+impl Zip64EndOfCentralDirectoryLocatorHeader {
+  fn find<R: Read + io::Seek>(
+    reader: &mut R,
+    eocd_abs_pos: u64,
+  ) -> Result<Zip64EndOfCentralDirectoryLocatorHeader> {
+    let size = size_of::<Zip64EndOfCentralDirectoryLocatorHeader>() as u64;
+    if eocd_abs_pos < size {
+      return Err(Error {
+        kind: ErrorKind::Other,
+      });
+    }
+    reader.seek(io::SeekFrom::Start(eocd_abs_pos - size))?;
+    let mut buf = [0u8; 20];
+    reader.read_exact(&mut buf)?;
+    let mut bytes = &buf[..];
+
+    let signature = read_le_u32(&mut bytes);
+    if signature != ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE {
+      return Err(Error {
+        kind: ErrorKind::Other,
+      });
+    }
+    let disk_with_zip64_eocd = read_le_u32(&mut bytes);
+    let zip64_eocd_offset = read_le_u64(&mut bytes);
+    let num_disks = read_le_u32(&mut bytes);
+
+    Ok(Zip64EndOfCentralDirectoryLocatorHeader {
+      signature: signature,
+      disk_with_zip64_eocd: disk_with_zip64_eocd,
+      zip64_eocd_offset: zip64_eocd_offset,
+      num_disks: num_disks,
+    })
+  }
+}
+
+impl Zip64EndOfCentralDirectoryHeader {
+  fn find<R: Read + io::Seek>(
+    reader: &mut R,
+    zip64_eocd_offset: u64,
+  ) -> Result<Zip64EndOfCentralDirectoryHeader> {
+    reader.seek(io::SeekFrom::Start(zip64_eocd_offset))?;
+    let mut buf = [0u8; 56];
+    reader.read_exact(&mut buf)?;
+    let mut bytes = &buf[..];
+
+    let signature = read_le_u32(&mut bytes);
+    if signature != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+      return Err(Error {
+        kind: ErrorKind::Other,
+      });
+    }
+    let size = read_le_u64(&mut bytes);
+    let made_by_ver = read_le_u16(&mut bytes);
+    let min_extract_ver = read_le_u16(&mut bytes);
+    let disk_number = read_le_u32(&mut bytes);
+    let start_disk = read_le_u32(&mut bytes);
+    let num_disk_entries = read_le_u64(&mut bytes);
+    let num_entries = read_le_u64(&mut bytes);
+    let central_dir_len = read_le_u64(&mut bytes);
+    let cendral_dir_offset = read_le_u64(&mut bytes);
+
+    Ok(Zip64EndOfCentralDirectoryHeader {
+      signature: signature,
+      size: size,
+      made_by_ver: made_by_ver,
+      min_extract_ver: min_extract_ver,
+      disk_number: disk_number,
+      start_disk: start_disk,
+      num_disk_entries: num_disk_entries,
+      num_entries: num_entries,
+      central_dir_len: central_dir_len,
+      cendral_dir_offset: cendral_dir_offset,
     })
   }
 }
@@ -289,7 +507,7 @@ impl ZipCentralDirectoryFile {
     let mut end = file_name_len as usize;
     let filename = match file_name_len {
       0 => String::new(),
-      _ => String::from_utf8(buf[start..end].to_vec())?,
+      _ => decode_zip_text(&buf[start..end], general_purpose_flag),
     };
     start = end;
     end = start + extra_field_len as usize;
@@ -301,20 +519,633 @@ impl ZipCentralDirectoryFile {
     end = start + comment_len as usize;
     let comment = match comment_len {
       0 => String::new(),
-      _ => String::from_utf8(buf[start..end].to_vec())?,
+      _ => decode_zip_text(&buf[start..end], general_purpose_flag),
     };
 
+    let (uncompressed_len64, compressed_len64, relative_offset_of_local_header64) =
+      read_zip64_extra_field(
+        &extra,
+        header.uncompressed_len,
+        header.compressed_len,
+        header.relative_offset_of_local_header,
+      );
+
     Ok(ZipCentralDirectoryFile {
       header: header,
       filename: filename,
       extra: extra,
       comment: comment,
+      uncompressed_len: uncompressed_len64,
+      compressed_len: compressed_len64,
+      relative_offset_of_local_header: relative_offset_of_local_header64,
     })
   }
 
   pub fn filename(&self) -> &String {
     &self.filename
   }
+
+  pub fn uncompressed_len(&self) -> u64 {
+    self.uncompressed_len
+  }
+
+  pub fn compressed_len(&self) -> u64 {
+    self.compressed_len
+  }
+
+  pub fn relative_offset_of_local_header(&self) -> u64 {
+    self.relative_offset_of_local_header
+  }
+
+  // Returns `None` for the all-zero date/time fields legacy tools write to
+  // mean "no timestamp".
+  pub fn last_modified(&self) -> Option<DateTime> {
+    DateTime::from_msdos(self.header.last_mod_date, self.header.last_mod_time)
+  }
+
+  // `made_by_ver`'s high byte identifies the host system that wrote the
+  // entry (3 == Unix), which tells us whether `external_attrib`'s high
+  // 16 bits hold a Unix file mode.
+  fn host_system(&self) -> u8 {
+    (self.header.made_by_ver >> 8) as u8
+  }
+
+  pub fn is_dir(&self) -> bool {
+    if self.filename.ends_with('/') {
+      return true;
+    }
+    // S_IFMT's file-type bits are a 4-bit code, not independent flags, so
+    // S_IFLNK/S_IFSOCK/etc. must be masked out before comparing.
+    self.host_system() == HOST_SYSTEM_UNIX
+      && (self.header.external_attrib >> 16) & S_IFMT == S_IFDIR
+  }
+
+  pub fn is_file(&self) -> bool {
+    if self.host_system() == HOST_SYSTEM_UNIX {
+      (self.header.external_attrib >> 16) & S_IFMT == S_IFREG
+    } else {
+      !self.is_dir()
+    }
+  }
+
+  // The Unix `st_mode` (file-type and permission bits) from
+  // `external_attrib`'s high 16 bits, or `None` when the entry wasn't written
+  // by a Unix host (`external_attrib` then holds unrelated DOS attribute bits).
+  pub fn unix_mode(&self) -> Option<u32> {
+    if self.host_system() == HOST_SYSTEM_UNIX {
+      Some((self.header.external_attrib >> 16) & 0xFFFF)
+    } else {
+      None
+    }
+  }
+}
+
+// An MS-DOS date/time pair as stored in ZIP local and central directory
+// headers, decoded to its individual components.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateTime {
+  year: u16,
+  month: u8,
+  day: u8,
+  hour: u8,
+  minute: u8,
+  second: u8,
+}
+
+impl DateTime {
+  fn from_msdos(date: u16, time: u16) -> Option<DateTime> {
+    if date == 0 && time == 0 {
+      return None;
+    }
+    Some(DateTime {
+      day: (date & 0x1F) as u8,
+      month: ((date >> 5) & 0x0F) as u8,
+      year: 1980 + ((date >> 9) & 0x7F),
+      second: (2 * (time & 0x1F)) as u8,
+      minute: ((time >> 5) & 0x3F) as u8,
+      hour: ((time >> 11) & 0x1F) as u8,
+    })
+  }
+
+  pub fn year(&self) -> u16 {
+    self.year
+  }
+
+  pub fn month(&self) -> u8 {
+    self.month
+  }
+
+  pub fn day(&self) -> u8 {
+    self.day
+  }
+
+  pub fn hour(&self) -> u8 {
+    self.hour
+  }
+
+  pub fn minute(&self) -> u8 {
+    self.minute
+  }
+
+  pub fn second(&self) -> u8 {
+    self.second
+  }
+}
+
+// MS-DOS date/time fields are decoded straight out of the raw bits, so a
+// corrupt or merely unusual archive can produce an out-of-range date (e.g.
+// month 0, or February 30th) that `chrono` refuses to represent; surface
+// that as `Err` instead of panicking.
+#[cfg(feature = "chrono")]
+impl TryFrom<DateTime> for chrono::NaiveDateTime {
+  type Error = ();
+
+  fn try_from(dt: DateTime) -> std::result::Result<chrono::NaiveDateTime, ()> {
+    chrono::NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)
+      .and_then(|date| date.and_hms_opt(dt.hour as u32, dt.minute as u32, dt.second as u32))
+      .ok_or(())
+  }
+}
+
+// Parses the ZIP64 extended-information extra field (header id 0x0001), if
+// present, widening whichever of the three fields were stored as the
+// 0xFFFFFFFF sentinel in the central directory header. The fields appear in
+// a fixed order (uncompressed_len, compressed_len, relative_offset) and only
+// the ones that overflowed are present, so each is read conditionally.
+fn read_zip64_extra_field(
+  extra: &[u8],
+  uncompressed_len: u32,
+  compressed_len: u32,
+  relative_offset_of_local_header: u32,
+) -> (u64, u64, u64) {
+  let mut uncompressed_len64 = uncompressed_len as u64;
+  let mut compressed_len64 = compressed_len as u64;
+  let mut relative_offset64 = relative_offset_of_local_header as u64;
+
+  let mut rest = extra;
+  while rest.len() >= 4 {
+    let header_id = read_le_u16(&mut rest);
+    let data_size = read_le_u16(&mut rest) as usize;
+    if rest.len() < data_size {
+      break;
+    }
+    let (mut data, remaining) = rest.split_at(data_size);
+    rest = remaining;
+    if header_id != ZIP64_EXTRA_FIELD_HEADER_ID {
+      continue;
+    }
+    if uncompressed_len == 0xFFFFFFFF && data.len() >= 8 {
+      uncompressed_len64 = read_le_u64(&mut data);
+    }
+    if compressed_len == 0xFFFFFFFF && data.len() >= 8 {
+      compressed_len64 = read_le_u64(&mut data);
+    }
+    if relative_offset_of_local_header == 0xFFFFFFFF && data.len() >= 8 {
+      relative_offset64 = read_le_u64(&mut data);
+    }
+  }
+
+  (uncompressed_len64, compressed_len64, relative_offset64)
+}
+
+impl ZipLocalFileHeader {
+  // Reads the local file header at the reader's current position and
+  // advances the reader past the filename/extra field, leaving it
+  // positioned at the start of the entry's data.
+  fn find<R: Read + io::Seek>(reader: &mut R) -> Result<ZipLocalFileHeader> {
+    let mut buf = [0u8; size_of::<ZipLocalFileHeader>()];
+    reader.read_exact(&mut buf)?;
+    let mut bytes = &buf[..];
+
+    let signature = read_le_u32(&mut bytes);
+    if signature != LOCAL_FILE_HEADER_SIGNATURE {
+      return Err(Error {
+        kind: ErrorKind::Other,
+      });
+    }
+    let min_extract_ver = read_le_u16(&mut bytes);
+    let general_purpose_flag = read_le_u16(&mut bytes);
+    let compression_method = read_le_u16(&mut bytes);
+    let last_mod_time = read_le_u16(&mut bytes);
+    let last_mod_date = read_le_u16(&mut bytes);
+    let crc32 = read_le_u32(&mut bytes);
+    let compressed_len = read_le_u32(&mut bytes);
+    let uncompressed_len = read_le_u32(&mut bytes);
+    let file_name_len = read_le_u16(&mut bytes);
+    let extra_field_len = read_le_u16(&mut bytes);
+
+    reader.seek(io::SeekFrom::Current(
+      file_name_len as i64 + extra_field_len as i64,
+    ))?;
+
+    Ok(ZipLocalFileHeader {
+      signature: signature,
+      min_extract_ver: min_extract_ver,
+      general_purpose_flag: general_purpose_flag,
+      compression_method: compression_method.into(),
+      last_mod_time: last_mod_time,
+      last_mod_date: last_mod_date,
+      crc32: crc32,
+      compressed_len: compressed_len,
+      uncompressed_len: uncompressed_len,
+      file_name_len: file_name_len,
+      extra_field_len: extra_field_len,
+    })
+  }
+}
+
+// Wraps a reader and verifies its IEEE CRC-32 against an expected value once
+// the inner reader is exhausted, so callers see a mismatch as a normal I/O
+// error on the final read rather than a separate verification step.
+struct Crc32Reader<R: Read> {
+  inner: R,
+  crc: u32,
+  expected_crc: u32,
+  done: bool,
+}
+
+impl<R: Read> Crc32Reader<R> {
+  fn new(inner: R, expected_crc: u32) -> Crc32Reader<R> {
+    Crc32Reader {
+      inner: inner,
+      crc: 0xFFFFFFFF,
+      expected_crc: expected_crc,
+      done: false,
+    }
+  }
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self.done {
+      return Ok(0);
+    }
+    let n = self.inner.read(buf)?;
+    if n == 0 {
+      self.done = true;
+      if self.crc ^ 0xFFFFFFFF != self.expected_crc {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          "CRC-32 mismatch",
+        ));
+      }
+      return Ok(0);
+    }
+    self.crc = update_crc32(self.crc, &buf[..n]);
+    Ok(n)
+  }
+}
+
+fn update_crc32(crc: u32, bytes: &[u8]) -> u32 {
+  let mut crc = crc;
+  for &byte in bytes {
+    crc ^= byte as u32;
+    for _ in 0..8 {
+      let mask = (crc & 1).wrapping_neg();
+      crc = (crc >> 1) ^ (0xEDB88320 & mask);
+    }
+  }
+  crc
+}
+
+// Writes ZIP archives to a non-seekable sink by following each entry's data
+// with a data descriptor, so sizes/CRC never need to be known up front.
+pub struct ZipWriter<W: Write> {
+  writer: W,
+  entries: Vec<ZipWriterEntry>,
+  current: Option<CurrentEntry>,
+  offset: u64,
+}
+
+struct ZipWriterEntry {
+  name: String,
+  compression_method: CompressionMethod,
+  crc32: u32,
+  compressed_len: u64,
+  uncompressed_len: u64,
+  offset: u64,
+}
+
+struct CurrentEntry {
+  name: String,
+  compression_method: CompressionMethod,
+  crc: u32,
+  compressed_len: u64,
+  uncompressed_len: u64,
+  offset: u64,
+  compressor: Option<Compress>,
+}
+
+impl<W: Write> ZipWriter<W> {
+  pub fn new(writer: W) -> ZipWriter<W> {
+    ZipWriter {
+      writer: writer,
+      entries: Vec::new(),
+      current: None,
+      offset: 0,
+    }
+  }
+
+  // Finishes whatever entry is in progress (if any) and begins a new one;
+  // call `Write::write` to supply its (uncompressed) body.
+  pub fn start_file(&mut self, name: &str, compression_method: CompressionMethod) -> Result<()> {
+    // Only methods this writer actually knows how to compress may be
+    // written; anything else would be labeled in the header but stored
+    // raw, producing an archive this crate's own reader can't extract.
+    match compression_method {
+      CompressionMethod::Uncompressed | CompressionMethod::Deflate => {}
+      _ => {
+        return Err(Error {
+          kind: ErrorKind::UnsupportedCompression(compression_method as u16),
+        })
+      }
+    }
+
+    self.finish_current_entry()?;
+
+    let offset = self.offset;
+    self.write_u32(LOCAL_FILE_HEADER_SIGNATURE)?;
+    self.write_u16(20)?; // min_extract_ver
+    self.write_u16(WRITER_GENERAL_PURPOSE_FLAG)?;
+    self.write_u16(compression_method as u16)?;
+    self.write_u16(0)?; // last_mod_time
+    self.write_u16(0)?; // last_mod_date
+    self.write_u32(0)?; // crc32 (see data descriptor)
+    self.write_u32(0)?; // compressed_len (see data descriptor)
+    self.write_u32(0)?; // uncompressed_len (see data descriptor)
+    self.write_u16(name.len() as u16)?;
+    self.write_u16(0)?; // extra_field_len
+    self.writer.write_all(name.as_bytes())?;
+    self.offset += name.len() as u64;
+
+    let compressor = match compression_method {
+      CompressionMethod::Deflate => Some(Compress::new(Compression::default(), false)),
+      _ => None,
+    };
+
+    self.current = Some(CurrentEntry {
+      name: name.to_string(),
+      compression_method: compression_method,
+      crc: 0xFFFFFFFF,
+      compressed_len: 0,
+      uncompressed_len: 0,
+      offset: offset,
+      compressor: compressor,
+    });
+
+    Ok(())
+  }
+
+  fn finish_current_entry(&mut self) -> Result<()> {
+    let mut current = match self.current.take() {
+      Some(current) => current,
+      None => return Ok(()),
+    };
+
+    if let Some(compressor) = current.compressor.as_mut() {
+      let mut out = [0u8; BUF_SIZE as usize];
+      loop {
+        let before_out = compressor.total_out();
+        let status = compressor
+          .compress(&[], &mut out, FlushCompress::Finish)
+          .map_err(|_| Error {
+            kind: ErrorKind::Other,
+          })?;
+        let produced = (compressor.total_out() - before_out) as usize;
+        if produced > 0 {
+          self.writer.write_all(&out[..produced])?;
+          self.offset += produced as u64;
+          current.compressed_len += produced as u64;
+        }
+        if let Status::StreamEnd = status {
+          break;
+        }
+      }
+    }
+
+    // `current.crc` is still in its inverted running state; finalize it the
+    // same way `Crc32Reader` does before writing or storing it anywhere.
+    let crc32 = current.crc ^ 0xFFFFFFFF;
+
+    self.write_u32(DATA_DESCRIPTOR_SIGNATURE)?;
+    self.write_u32(crc32)?;
+    self.write_u32(current.compressed_len as u32)?;
+    self.write_u32(current.uncompressed_len as u32)?;
+
+    self.entries.push(ZipWriterEntry {
+      name: current.name,
+      compression_method: current.compression_method,
+      crc32: crc32,
+      compressed_len: current.compressed_len,
+      uncompressed_len: current.uncompressed_len,
+      offset: current.offset,
+    });
+
+    Ok(())
+  }
+
+  // Writes the central directory and end-of-central-directory records,
+  // promoting to the ZIP64 variants of either whenever an entry or the
+  // archive as a whole overflows the classic 32-bit/16-bit fields.
+  pub fn finish(mut self) -> Result<W> {
+    self.finish_current_entry()?;
+
+    let central_dir_offset = self.offset;
+    let mut any_entry_zip64 = false;
+    for i in 0..self.entries.len() {
+      let needs_zip64 = self.entries[i].compressed_len > u32::MAX as u64
+        || self.entries[i].uncompressed_len > u32::MAX as u64
+        || self.entries[i].offset > u32::MAX as u64;
+      any_entry_zip64 = any_entry_zip64 || needs_zip64;
+      self.write_central_dir_entry(i, needs_zip64)?;
+    }
+    let central_dir_len = self.offset - central_dir_offset;
+    let num_entries = self.entries.len() as u64;
+
+    let needs_zip64 = any_entry_zip64
+      || num_entries > 0xFFFF
+      || central_dir_len > u32::MAX as u64
+      || central_dir_offset > u32::MAX as u64;
+
+    if needs_zip64 {
+      let zip64_eocd_offset = self.offset;
+      self.write_zip64_eocd(central_dir_offset, central_dir_len, num_entries)?;
+      self.write_zip64_eocd_locator(zip64_eocd_offset)?;
+    }
+    self.write_eocd(central_dir_offset, central_dir_len, num_entries, needs_zip64)?;
+
+    Ok(self.writer)
+  }
+
+  fn write_central_dir_entry(&mut self, i: usize, needs_zip64: bool) -> Result<()> {
+    let (name, compression_method, crc32, compressed_len, uncompressed_len, offset) = {
+      let entry = &self.entries[i];
+      (
+        entry.name.clone(),
+        entry.compression_method,
+        entry.crc32,
+        entry.compressed_len,
+        entry.uncompressed_len,
+        entry.offset,
+      )
+    };
+
+    self.write_u32(CENTRAL_DIRECTORY_SIGNATURE)?;
+    self.write_u16(20)?; // made_by_ver
+    self.write_u16(20)?; // min_extract_ver
+    self.write_u16(WRITER_GENERAL_PURPOSE_FLAG)?;
+    self.write_u16(compression_method as u16)?;
+    self.write_u16(0)?; // last_mod_time
+    self.write_u16(0)?; // last_mod_date
+    self.write_u32(crc32)?;
+    if needs_zip64 {
+      self.write_u32(0xFFFFFFFF)?; // compressed_len
+      self.write_u32(0xFFFFFFFF)?; // uncompressed_len
+    } else {
+      self.write_u32(compressed_len as u32)?;
+      self.write_u32(uncompressed_len as u32)?;
+    }
+    self.write_u16(name.len() as u16)?;
+    self.write_u16(if needs_zip64 { 28 } else { 0 })?; // extra_field_len
+    self.write_u16(0)?; // comment_len
+    self.write_u16(0)?; // start_disk
+    self.write_u16(0)?; // internal_attrib
+    self.write_u32(0)?; // external_attrib
+    if needs_zip64 {
+      self.write_u32(0xFFFFFFFF)?; // relative_offset_of_local_header
+    } else {
+      self.write_u32(offset as u32)?;
+    }
+    self.writer.write_all(name.as_bytes())?;
+    self.offset += name.len() as u64;
+
+    if needs_zip64 {
+      self.write_u16(ZIP64_EXTRA_FIELD_HEADER_ID)?;
+      self.write_u16(24)?; // data size
+      self.write_u64(uncompressed_len)?;
+      self.write_u64(compressed_len)?;
+      self.write_u64(offset)?;
+    }
+
+    Ok(())
+  }
+
+  fn write_zip64_eocd(
+    &mut self,
+    central_dir_offset: u64,
+    central_dir_len: u64,
+    num_entries: u64,
+  ) -> Result<()> {
+    self.write_u32(ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+    self.write_u64(44)?; // size of the record following this field
+    self.write_u16(20)?; // made_by_ver
+    self.write_u16(45)?; // min_extract_ver
+    self.write_u32(0)?; // disk_number
+    self.write_u32(0)?; // start_disk
+    self.write_u64(num_entries)?; // num_disk_entries
+    self.write_u64(num_entries)?;
+    self.write_u64(central_dir_len)?;
+    self.write_u64(central_dir_offset)?;
+    Ok(())
+  }
+
+  fn write_zip64_eocd_locator(&mut self, zip64_eocd_offset: u64) -> Result<()> {
+    self.write_u32(ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE)?;
+    self.write_u32(0)?; // disk_with_zip64_eocd
+    self.write_u64(zip64_eocd_offset)?;
+    self.write_u32(1)?; // num_disks
+    Ok(())
+  }
+
+  fn write_eocd(
+    &mut self,
+    central_dir_offset: u64,
+    central_dir_len: u64,
+    num_entries: u64,
+    needs_zip64: bool,
+  ) -> Result<()> {
+    self.write_u32(END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+    self.write_u16(0)?; // disk_number
+    self.write_u16(0)?; // start_disk
+    if needs_zip64 {
+      self.write_u16(0xFFFF)?;
+      self.write_u16(0xFFFF)?;
+      self.write_u32(0xFFFFFFFF)?;
+      self.write_u32(0xFFFFFFFF)?;
+    } else {
+      self.write_u16(num_entries as u16)?;
+      self.write_u16(num_entries as u16)?;
+      self.write_u32(central_dir_len as u32)?;
+      self.write_u32(central_dir_offset as u32)?;
+    }
+    self.write_u16(0)?; // comment_len
+    Ok(())
+  }
+
+  fn write_u16(&mut self, v: u16) -> Result<()> {
+    self.writer.write_all(&v.to_le_bytes())?;
+    self.offset += 2;
+    Ok(())
+  }
+
+  fn write_u32(&mut self, v: u32) -> Result<()> {
+    self.writer.write_all(&v.to_le_bytes())?;
+    self.offset += 4;
+    Ok(())
+  }
+
+  fn write_u64(&mut self, v: u64) -> Result<()> {
+    self.writer.write_all(&v.to_le_bytes())?;
+    self.offset += 8;
+    Ok(())
+  }
+}
+
+impl<W: Write> Write for ZipWriter<W> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    let current = self
+      .current
+      .as_mut()
+      .ok_or_else(|| io::Error::other("start_file must be called before write"))?;
+    current.crc = update_crc32(current.crc, buf);
+    current.uncompressed_len += buf.len() as u64;
+
+    match current.compressor.as_mut() {
+      Some(compressor) => {
+        let mut input = buf;
+        let mut out = [0u8; BUF_SIZE as usize];
+        while !input.is_empty() {
+          let before_in = compressor.total_in();
+          let before_out = compressor.total_out();
+          let status = compressor
+            .compress(input, &mut out, FlushCompress::None)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+          let consumed = (compressor.total_in() - before_in) as usize;
+          let produced = (compressor.total_out() - before_out) as usize;
+          if produced > 0 {
+            self.writer.write_all(&out[..produced])?;
+            self.offset += produced as u64;
+            current.compressed_len += produced as u64;
+          }
+          input = &input[consumed..];
+          if let Status::StreamEnd = status {
+            break;
+          }
+        }
+      }
+      None => {
+        self.writer.write_all(buf)?;
+        self.offset += buf.len() as u64;
+        current.compressed_len += buf.len() as u64;
+      }
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.writer.flush()
+  }
 }
 
 impl From<u16> for CompressionMethod {
@@ -322,6 +1153,8 @@ impl From<u16> for CompressionMethod {
     match n {
       0 => CompressionMethod::Uncompressed,
       8 => CompressionMethod::Deflate,
+      12 => CompressionMethod::Bzip2,
+      93 => CompressionMethod::Zstd,
       _ => CompressionMethod::Unsupported,
     }
   }
@@ -344,3 +1177,145 @@ fn read_le_u64(input: &mut &[u8]) -> u64 {
   *input = rest;
   u64::from_le_bytes(int_bytes.try_into().unwrap())
 }
+
+// Bit 11 of the general-purpose flag ("language encoding flag", EFS) marks
+// the filename/comment as UTF-8; when it's clear, legacy tools wrote them in
+// IBM Code Page 437.
+const UTF8_FLAG: u16 = 0x0800;
+
+// High half (0x80-0xFF) of IBM Code Page 437; bytes below 0x80 are ASCII.
+const CP437_HIGH_HALF: [char; 128] = [
+  'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', 'É', 'æ', 'Æ',
+  'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ',
+  'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕',
+  '╣', '║', '╗', '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦',
+  '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐',
+  '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±',
+  '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{00a0}',
+];
+
+// Decodes bytes as CP437, an infallible conversion since every byte value
+// maps to some Unicode scalar.
+fn decode_cp437(bytes: &[u8]) -> String {
+  bytes
+    .iter()
+    .map(|&b| {
+      if b < 0x80 {
+        b as char
+      } else {
+        CP437_HIGH_HALF[(b - 0x80) as usize]
+      }
+    })
+    .collect()
+}
+
+// Decodes a filename/comment per the general-purpose flag's UTF-8 bit,
+// falling back to CP437 if a UTF-8-flagged entry turns out not to be valid
+// UTF-8 after all.
+fn decode_zip_text(bytes: &[u8], general_purpose_flag: u16) -> String {
+  if general_purpose_flag & UTF8_FLAG != 0 {
+    decode_best_effort(bytes)
+  } else {
+    decode_cp437(bytes)
+  }
+}
+
+// Used where no general-purpose flag is available (the EOCD archive
+// comment): try UTF-8 first, falling back to CP437.
+fn decode_best_effort(bytes: &[u8]) -> String {
+  match String::from_utf8(bytes.to_vec()) {
+    Ok(s) => s,
+    Err(e) => decode_cp437(&e.into_bytes()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  // Hand-crafts an EOCD preceded by a ZIP64 locator and ZIP64 EOCD record
+  // (with an empty central directory) so `ZipEndOfCentralDirectory::find`
+  // exercises the ZIP64 offset math instead of the classic fixed-size path.
+  #[test]
+  fn zip64_eocd_widens_entry_count_and_offsets() {
+    let central_dir_offset: u64 = 0;
+    let central_dir_len: u64 = 0;
+    // Overflows the classic EOCD's 16-bit entry count.
+    let num_entries: u64 = 70000;
+
+    let mut buf = Vec::new();
+
+    let zip64_eocd_offset = buf.len() as u64;
+    buf.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    buf.extend_from_slice(&44u64.to_le_bytes()); // size of record following this field
+    buf.extend_from_slice(&45u16.to_le_bytes()); // made_by_ver
+    buf.extend_from_slice(&45u16.to_le_bytes()); // min_extract_ver
+    buf.extend_from_slice(&0u32.to_le_bytes()); // disk_number
+    buf.extend_from_slice(&0u32.to_le_bytes()); // start_disk
+    buf.extend_from_slice(&num_entries.to_le_bytes()); // num_disk_entries
+    buf.extend_from_slice(&num_entries.to_le_bytes());
+    buf.extend_from_slice(&central_dir_len.to_le_bytes());
+    buf.extend_from_slice(&central_dir_offset.to_le_bytes());
+
+    buf.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes()); // disk_with_zip64_eocd
+    buf.extend_from_slice(&zip64_eocd_offset.to_le_bytes());
+    buf.extend_from_slice(&1u32.to_le_bytes()); // num_disks
+
+    buf.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    buf.extend_from_slice(&0xFFFFu16.to_le_bytes()); // disk_number
+    buf.extend_from_slice(&0xFFFFu16.to_le_bytes()); // start_disk
+    buf.extend_from_slice(&0xFFFFu16.to_le_bytes()); // num_disk_entries
+    buf.extend_from_slice(&0xFFFFu16.to_le_bytes()); // num_entries
+    buf.extend_from_slice(&0xFFFFFFFFu32.to_le_bytes()); // central_dir_len
+    buf.extend_from_slice(&0xFFFFFFFFu32.to_le_bytes()); // central_dir_offset
+    buf.extend_from_slice(&0u16.to_le_bytes()); // comment_len
+
+    let mut cursor = Cursor::new(buf);
+    let eocd = ZipEndOfCentralDirectory::find(&mut cursor).expect("should parse ZIP64 EOCD");
+
+    assert_eq!(eocd.num_entries, num_entries);
+    assert_eq!(eocd.central_dir_len, central_dir_len);
+    assert_eq!(eocd.central_dir_offset, central_dir_offset);
+  }
+
+  // Writes a stored and a deflated entry with `ZipWriter`, then reads the
+  // resulting bytes back with `ZipArchive`/`by_name`, checking that both the
+  // extracted contents and the CRC-32 check survive the round trip.
+  #[test]
+  fn round_trip_write_then_read() {
+    let mut writer = ZipWriter::new(Vec::new());
+
+    writer
+      .start_file("stored.bin", CompressionMethod::Uncompressed)
+      .unwrap();
+    writer.write_all(&[1, 2, 3, 4, 5]).unwrap();
+
+    writer
+      .start_file("hello.txt", CompressionMethod::Deflate)
+      .unwrap();
+    writer.write_all(b"hello, zip writer!").unwrap();
+
+    let bytes = writer.finish().unwrap();
+
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+    assert_eq!(archive.files().len(), 2);
+
+    let mut contents = Vec::new();
+    archive
+      .by_name("stored.bin")
+      .unwrap()
+      .read_to_end(&mut contents)
+      .unwrap();
+    assert_eq!(contents, vec![1, 2, 3, 4, 5]);
+
+    let mut contents = Vec::new();
+    archive
+      .by_name("hello.txt")
+      .unwrap()
+      .read_to_end(&mut contents)
+      .unwrap();
+    assert_eq!(contents, b"hello, zip writer!");
+  }
+}